@@ -1,5 +1,5 @@
 use std::fs::{File, create_dir_all, read_dir};
-use std::io::Write;
+use std::io::{Cursor, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{self};
 use std::sync::Mutex;
@@ -7,22 +7,50 @@ use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
+use arrow::array::{Array, BinaryArray, StringArray, StructArray};
+use arrow::datatypes::{DataType, Schema};
 use arrow::ipc::reader::StreamReader;
 use arrow::record_batch::RecordBatch;
+use bytes::Bytes;
 use clap::{ArgAction, Parser, ValueEnum};
+use futures::StreamExt;
+use object_store::ObjectStore;
 use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
 use parquet::file::properties::WriterProperties;
 use polars::prelude::*;
 use rayon::{ThreadPoolBuilder, prelude::*};
+use url::Url;
 
 static UNIQUE_FILENAME_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+/// Size of a tar block: the header and all payloads are aligned to this.
+const TAR_BLOCK_SIZE: u64 = 512;
+
 #[derive(Clone, Debug, Copy, PartialEq, Eq, ValueEnum)]
 enum Format {
     Arrow,
     Parquet,
 }
 
+#[derive(Clone, Debug, Copy, PartialEq, Eq, ValueEnum)]
+enum MetadataFormat {
+    /// Comma-separated values with a header row.
+    Csv,
+    /// Line-delimited JSON, one object per row (the common HF/ASR format).
+    Jsonl,
+    /// Parquet with a typed schema.
+    Parquet,
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputMode {
+    /// Write each audio clip as a loose file in the output directory.
+    Files,
+    /// Pack audio and transcriptions into sharded WebDataset tar archives.
+    Tar,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, long_about = None)]
 struct Args {
@@ -47,27 +75,72 @@ struct Args {
     #[arg(long, default_value_t = 3)]
     threads: usize,
 
-    /// CSV file where transcriptions should be written
+    /// File where transcriptions should be written
     #[arg(long, action = ArgAction::Set)]
     metadata_file: Option<PathBuf>,
+
+    /// Serialization format for the metadata manifest
+    #[arg(long)]
+    #[clap(value_enum, default_value_t = MetadataFormat::Csv)]
+    metadata_format: MetadataFormat,
+
+    /// Read the input incrementally in record batches of this size, flushing
+    /// audio to disk per batch instead of materializing the whole file as a
+    /// DataFrame. Keeps memory bounded on multi-GB local shards (remote inputs
+    /// are still fetched whole before decoding).
+    #[arg(long)]
+    batch_size: Option<usize>,
+
+    /// How extracted audio is laid out on disk
+    #[arg(long)]
+    #[clap(value_enum, default_value_t = OutputMode::Files)]
+    output_mode: OutputMode,
+
+    /// Roll to a new tar shard once the current one exceeds this many bytes
+    /// (only used with `--output-mode tar`)
+    #[arg(long, default_value_t = 1_000_000_000)]
+    shard_size: u64,
+
+    /// Lay out extracted files into Hive-style `<column>=<value>/` subdirectories
+    /// keyed by this metadata column (e.g. `split`, `speaker`, `language`)
+    #[arg(long)]
+    partition_by: Option<String>,
+}
+
+/// Accumulated metadata rows: `(file_name, transcription, partition value)`.
+/// The partition value is `None` unless `--partition-by` is set.
+type MetaRows = Mutex<Vec<(String, String, Option<String>)>>;
+
+/// Top-level columns projected out of each input: always `audio` and
+/// `transcription`, plus the `--partition-by` column when requested.
+fn projection_columns(partition_by: Option<&str>) -> Vec<String> {
+    let mut columns = vec!["audio".to_string(), "transcription".to_string()];
+    if let Some(column) = partition_by {
+        columns.push(column.to_string());
+    }
+    columns
 }
 
-fn arrow_to_parquet(filename: &Path) -> Result<DataFrame> {
+fn arrow_to_parquet(filename: &Path, columns: &[String]) -> Result<DataFrame> {
     let file = File::open(filename)
         .with_context(|| format!("Failed to open arrow file: {}", filename.display()))?;
+    arrow_to_parquet_from(file, columns)
+}
+
+fn arrow_to_parquet_from<R: Read>(reader: R, columns: &[String]) -> Result<DataFrame> {
     let reader =
-        StreamReader::try_new(file, None).context("Failed to create arrow stream reader")?;
+        StreamReader::try_new(reader, None).context("Failed to create arrow stream reader")?;
 
     let batches: Vec<RecordBatch> = reader
         .collect::<std::result::Result<_, _>>()
         .context("Failed to collect record batches from arrow file")?;
-    let df = batches_to_parquet(&batches)
+    let df = batches_to_parquet(&batches, columns)
         .context("Failed to convert arrow batches to parquet for DataFrame")?;
 
     Ok(df)
 }
 
-fn batches_to_parquet(batches: &[RecordBatch]) -> Result<DataFrame> {
+fn batches_to_parquet(batches: &[RecordBatch], columns: &[String]) -> Result<DataFrame> {
     // In-memory buffer to avoid writing to a temporary file on disk
     let tmp_file = tempfile::tempfile()?;
 
@@ -83,19 +156,22 @@ fn batches_to_parquet(batches: &[RecordBatch]) -> Result<DataFrame> {
 
     // Read in parquet file and unnest the audio column
     let df = ParquetReader::new(tmp_file)
-        .with_columns(Some(vec!["audio".to_string(), "transcription".to_string()]))
+        .with_columns(Some(columns.to_vec()))
         .finish()?
         .unnest(["audio"], None)?;
 
     Ok(df)
 }
 
-fn read_parquet(filename: &Path) -> Result<DataFrame> {
+fn read_parquet(filename: &Path, columns: &[String]) -> Result<DataFrame> {
     let file = File::open(filename)
         .with_context(|| format!("Failed to open parquet file: {}", filename.display()))?;
+    read_parquet_from(file, columns)
+}
 
-    let df = ParquetReader::new(file)
-        .with_columns(Some(vec!["audio".to_string(), "transcription".to_string()]))
+fn read_parquet_from<R: MmapBytesReader + 'static>(reader: R, columns: &[String]) -> Result<DataFrame> {
+    let df = ParquetReader::new(reader)
+        .with_columns(Some(columns.to_vec()))
         .finish()
         .context("Failed to read parquet file into DataFrame")?
         .unnest(["audio"], None)?;
@@ -103,6 +179,272 @@ fn read_parquet(filename: &Path) -> Result<DataFrame> {
     Ok(df)
 }
 
+/// A resolved input shard: either a local file or bytes fetched from an object
+/// store. Remote bytes are fed into the same readers as local files.
+enum InputData {
+    Local(PathBuf),
+    Remote { name: String, bytes: Bytes },
+}
+
+impl InputData {
+    /// Human-readable name for log messages.
+    fn display_name(&self) -> String {
+        match self {
+            InputData::Local(path) => path.display().to_string(),
+            InputData::Remote { name, .. } => name.clone(),
+        }
+    }
+}
+
+/// Whether `arg` carries a non-local URI scheme (`s3://`, `gs://`, `http(s)://`).
+/// `file://` is treated as local.
+fn remote_scheme(arg: &str) -> Option<&str> {
+    arg.split_once("://")
+        .map(|(scheme, _)| scheme)
+        .filter(|scheme| *scheme != "file")
+}
+
+/// Build an `object_store` handle plus the object path for a URI.
+fn store_for(uri: &str) -> Result<(Box<dyn ObjectStore>, object_store::path::Path)> {
+    let url = Url::parse(uri).with_context(|| format!("Invalid input URL: {uri}"))?;
+    let (store, path) = object_store::parse_url(&url)
+        .with_context(|| format!("Unsupported object store URL: {uri}"))?;
+    Ok((store, path))
+}
+
+/// Run a future to completion on a throwaway current-thread runtime. The rest
+/// of the tool is synchronous, so object-store I/O is bridged here.
+fn block_on<F: std::future::Future>(future: F) -> Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to build async runtime for object store I/O")?;
+    Ok(runtime.block_on(future))
+}
+
+/// Fetch a single remote object into memory.
+fn fetch_remote(uri: &str) -> Result<InputData> {
+    let (store, path) = store_for(uri)?;
+    let bytes = block_on(async {
+        let result = store.get(&path).await?;
+        result.bytes().await
+    })?
+    .with_context(|| format!("Failed to fetch {uri}"))?;
+    Ok(InputData::Remote {
+        name: uri.to_string(),
+        bytes,
+    })
+}
+
+/// List `*.parquet`/`*.arrow` objects under a remote prefix and fetch each.
+fn fetch_remote_dir(prefix_uri: &str) -> Result<Vec<InputData>> {
+    let (store, prefix) = store_for(prefix_uri)?;
+    let locations = block_on(async {
+        let mut stream = store.list(Some(&prefix));
+        let mut out = Vec::new();
+        while let Some(meta) = stream.next().await {
+            out.push(meta?.location);
+        }
+        Ok::<_, object_store::Error>(out)
+    })?
+    .with_context(|| format!("Failed to list {prefix_uri}"))?;
+
+    let wanted: Vec<_> = locations
+        .into_iter()
+        .filter(|loc| {
+            let name = loc.as_ref();
+            name.ends_with(".parquet") || name.ends_with(".arrow")
+        })
+        .collect();
+
+    let mut inputs = Vec::with_capacity(wanted.len());
+    for location in wanted {
+        let bytes = block_on(async {
+            let result = store.get(&location).await?;
+            result.bytes().await
+        })?
+        .with_context(|| format!("Failed to fetch {location}"))?;
+        inputs.push(InputData::Remote {
+            name: location.to_string(),
+            bytes,
+        });
+    }
+    Ok(inputs)
+}
+
+/// A destination for extracted audio clips. Implementors persist one clip and
+/// return the name recorded in the metadata manifest.
+trait AudioSink: Sync {
+    /// Persist a single audio record, returning the metadata `file_name`.
+    ///
+    /// `suggested_name` is the clip's natural `stem.ext` file name; sinks that
+    /// assign their own keys (e.g. tar shards) are free to ignore it.
+    fn write(&self, suggested_name: &str, audio: &[u8], transcription: &str) -> Result<String>;
+
+    /// Flush any buffered state once extraction is complete. No-op for sinks
+    /// that write eagerly.
+    fn finish(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes each clip as a loose file in `output_dir`, preserving the original
+/// collision-avoidance behavior of [`write_file`].
+struct FilesSink<'a> {
+    output_dir: &'a Path,
+}
+
+impl AudioSink for FilesSink<'_> {
+    fn write(&self, suggested_name: &str, audio: &[u8], _transcription: &str) -> Result<String> {
+        let audio_filename = self.output_dir.join(suggested_name);
+        // `suggested_name` may carry a partition subdirectory; create it lazily.
+        // `create_dir_all` is idempotent and safe to call from parallel workers.
+        if let Some(parent) = audio_filename.parent() {
+            create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let written_path = write_file(&audio_filename, audio)?;
+        // Record the path relative to the output root so the manifest's
+        // `file_name` still locates the clip once `--partition-by` nests it in a
+        // `<column>=<value>/` subdirectory.
+        let final_name = written_path
+            .strip_prefix(self.output_dir)
+            .unwrap_or(&written_path)
+            .to_string_lossy()
+            .into_owned();
+        Ok(final_name)
+    }
+}
+
+/// Packs clips into sharded WebDataset tar archives. Shard writing is
+/// serialized behind a mutex so extraction can stay parallel.
+struct TarSink {
+    inner: Mutex<ShardWriter>,
+}
+
+impl TarSink {
+    fn new(output_dir: &Path, shard_size: u64) -> Result<Self> {
+        Ok(Self {
+            inner: Mutex::new(ShardWriter::new(output_dir, shard_size)?),
+        })
+    }
+}
+
+impl AudioSink for TarSink {
+    fn write(&self, suggested_name: &str, audio: &[u8], transcription: &str) -> Result<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .write_record(suggested_name, audio, transcription)
+    }
+
+    /// Flush and close the final shard.
+    fn finish(&self) -> Result<()> {
+        self.inner.lock().unwrap().finish()
+    }
+}
+
+/// Serialized writer that rolls `shard-%06d.tar` archives once a byte
+/// threshold is exceeded. Each record contributes two consecutive entries
+/// sharing a sequential key: `<key>.wav` and `<key>.txt`.
+struct ShardWriter {
+    output_dir: PathBuf,
+    shard_size: u64,
+    mtime: u64,
+    shard_index: usize,
+    current_bytes: u64,
+    key: u64,
+    builder: Option<tar::Builder<File>>,
+}
+
+impl ShardWriter {
+    fn new(output_dir: &Path, shard_size: u64) -> Result<Self> {
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System time is before UNIX_EPOCH")?
+            .as_secs();
+        Ok(Self {
+            output_dir: output_dir.to_path_buf(),
+            shard_size,
+            mtime,
+            shard_index: 0,
+            current_bytes: 0,
+            key: 0,
+            builder: None,
+        })
+    }
+
+    /// Open the first shard, or roll to a fresh one once the current shard has
+    /// grown past `shard_size`.
+    fn roll_if_needed(&mut self) -> Result<()> {
+        if self.builder.is_none() || self.current_bytes > self.shard_size {
+            self.finish()?;
+            let shard_name = format!("shard-{:06}.tar", self.shard_index);
+            let path = self.output_dir.join(&shard_name);
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create tar shard: {}", path.display()))?;
+            self.builder = Some(tar::Builder::new(file));
+            self.shard_index += 1;
+            self.current_bytes = 0;
+        }
+        Ok(())
+    }
+
+    /// Append a single tar entry with mode 0644 and the shard's mtime.
+    fn append(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let builder = self
+            .builder
+            .as_mut()
+            .expect("shard builder must be initialized before append");
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(self.mtime);
+        builder
+            .append_data(&mut header, name, data)
+            .with_context(|| format!("Failed to append tar entry: {name}"))?;
+        // Each entry occupies a 512-byte header plus its payload padded up to a
+        // 512-byte block boundary; count that so `--shard-size` tracks the real
+        // on-disk shard size rather than just the raw payload bytes.
+        let payload_blocks = (data.len() as u64).div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+        self.current_bytes += TAR_BLOCK_SIZE + payload_blocks;
+        Ok(())
+    }
+
+    fn write_record(
+        &mut self,
+        suggested_name: &str,
+        audio: &[u8],
+        transcription: &str,
+    ) -> Result<String> {
+        self.roll_if_needed()?;
+        // Preserve any `<column>=<value>/` partition prefix carried in
+        // `suggested_name` so `--partition-by` still groups records in tar mode;
+        // the stem itself stays a collision-free sequential key.
+        let sequential = format!("{:08}", self.key);
+        self.key += 1;
+        let key = match Path::new(suggested_name).parent().and_then(Path::to_str) {
+            Some(prefix) if !prefix.is_empty() => format!("{prefix}/{sequential}"),
+            _ => sequential,
+        };
+        let wav_name = format!("{key}.wav");
+        self.append(&wav_name, audio)?;
+        self.append(&format!("{key}.txt"), transcription.as_bytes())?;
+        Ok(wav_name)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(builder) = self.builder.take() {
+            builder
+                .into_inner()
+                .context("Failed to finalize tar shard")?
+                .sync_all()
+                .context("Failed to flush tar shard to disk")?;
+        }
+        Ok(())
+    }
+}
+
 fn write_file(filename: &Path, data: &[u8]) -> Result<PathBuf> {
     // Choose a new filename with timestamp prefix if the target already exists.
     let mut target = filename.to_path_buf();
@@ -130,19 +472,45 @@ fn write_file(filename: &Path, data: &[u8]) -> Result<PathBuf> {
     Ok(target)
 }
 
+/// Build the relative output name for a clip, prepending a Hive-style
+/// `<column>=<value>/` partition directory when partitioning is enabled.
+fn partitioned_name(filename: &str, partition: Option<(&str, &str)>) -> String {
+    match partition {
+        Some((column, value)) => format!("{column}={value}/{filename}"),
+        None => filename.to_string(),
+    }
+}
+
+fn dataframe_from_input(
+    input: &InputData,
+    format: Format,
+    columns: &[String],
+) -> Result<DataFrame> {
+    match (format, input) {
+        (Format::Arrow, InputData::Local(path)) => arrow_to_parquet(path, columns)
+            .with_context(|| format!("Error processing arrow file {}", path.display())),
+        (Format::Arrow, InputData::Remote { name, bytes }) => {
+            arrow_to_parquet_from(Cursor::new(bytes.clone()), columns)
+                .with_context(|| format!("Error processing arrow input {name}"))
+        }
+        (Format::Parquet, InputData::Local(path)) => read_parquet(path, columns)
+            .with_context(|| format!("Error processing parquet file {}", path.display())),
+        (Format::Parquet, InputData::Remote { name, bytes }) => {
+            read_parquet_from(Cursor::new(bytes.clone()), columns)
+                .with_context(|| format!("Error processing parquet input {name}"))
+        }
+    }
+}
+
 fn process_file(
-    filename: &Path,
+    input: &InputData,
     format: Format,
-    output_dir: &Path,
-    metadata_records: &Mutex<Vec<(String, String)>>,
+    sink: &dyn AudioSink,
+    partition_by: Option<&str>,
+    metadata_records: &MetaRows,
 ) -> Result<usize> {
-    // Convert the file to a DataFrame
-    let df = match format {
-        Format::Arrow => arrow_to_parquet(filename)
-            .with_context(|| format!("Error processing arrow file {}", filename.display()))?,
-        Format::Parquet => read_parquet(filename)
-            .with_context(|| format!("Error processing parquet file {}", filename.display()))?,
-    };
+    let columns = projection_columns(partition_by);
+    let df = dataframe_from_input(input, format, &columns)?;
 
     // Extract the series from the DataFrame
     let path_series = df.column("path")?.str()?;
@@ -151,6 +519,17 @@ fn process_file(
 
     let num_rows = df.height();
 
+    // Materialize partition values up front so the parallel write loop only
+    // touches plain owned data.
+    let partition_values: Option<Vec<Option<String>>> = match partition_by {
+        Some(column) => {
+            let casted = df.column(column)?.cast(&polars::prelude::DataType::String)?;
+            let values = casted.str()?;
+            Some((0..num_rows).map(|i| values.get(i).map(str::to_string)).collect())
+        }
+        None => None,
+    };
+
     let records: Vec<_> = (0..num_rows)
         .into_par_iter()
         .filter_map(|i| {
@@ -159,16 +538,16 @@ fn process_file(
                 transcription_series.get(i),
                 array_series.get(i),
             ) {
-                Some((path_val, transcription, array_series_inner))
+                Some((i, path_val, transcription, array_series_inner))
             } else {
                 None
             }
         })
         .collect();
 
-    let local_metadata: Vec<(String, String)> = records
+    let local_metadata: Vec<(String, String, Option<String>)> = records
         .par_iter()
-        .map(|(path_val, transcription, array_series_inner)| {
+        .map(|(i, path_val, transcription, array_series_inner)| {
             let original_path = Path::new(path_val);
             let file_stem = original_path.file_stem().unwrap_or_default();
             let extension = original_path.extension().unwrap_or_default();
@@ -178,24 +557,231 @@ fn process_file(
                 file_stem.to_string_lossy(),
                 extension.to_string_lossy()
             );
-            let audio_filename = output_dir.join(&audio_filename_str);
+            let partition = partition_values
+                .as_ref()
+                .and_then(|values| values[*i].clone());
+            let suggested = partitioned_name(
+                &audio_filename_str,
+                partition_by.zip(partition.as_deref()),
+            );
             let audio_data: &[u8] = array_series_inner;
-            let written_path =
-                write_file(&audio_filename, audio_data).expect("Failed to write audio file");
-            let final_name = written_path
-                .file_name()
-                .map(|name| name.to_string_lossy().into_owned())
-                .unwrap_or_else(|| written_path.to_string_lossy().into_owned());
-
-            (final_name, transcription.to_string())
+            let final_name = sink.write(&suggested, audio_data, transcription)?;
+
+            Ok((final_name, transcription.to_string(), partition))
         })
-        .collect();
+        .collect::<Result<_>>()?;
 
     metadata_records.lock().unwrap().extend(local_metadata);
 
     Ok(num_rows)
 }
 
+/// Verify that the `audio` struct column exposes the `path` (Utf8) and
+/// `bytes` (Binary) children we rely on, plus a top-level `transcription`
+/// column. Run once per file before streaming so a malformed schema fails
+/// fast rather than panicking mid-batch.
+fn validate_audio_schema(schema: &Schema) -> Result<()> {
+    let audio = schema
+        .field_with_name("audio")
+        .context("input is missing the `audio` column")?;
+    let DataType::Struct(fields) = audio.data_type() else {
+        anyhow::bail!("`audio` column is not a struct");
+    };
+    let path = fields
+        .iter()
+        .find(|f| f.name() == "path")
+        .context("`audio` struct is missing the `path` field")?;
+    if path.data_type() != &DataType::Utf8 {
+        anyhow::bail!("`audio.path` is not a Utf8 field");
+    }
+    let bytes = fields
+        .iter()
+        .find(|f| f.name() == "bytes")
+        .context("`audio` struct is missing the `bytes` field")?;
+    if bytes.data_type() != &DataType::Binary {
+        anyhow::bail!("`audio.bytes` is not a Binary field");
+    }
+    schema
+        .field_with_name("transcription")
+        .context("input is missing the `transcription` column")?;
+
+    Ok(())
+}
+
+/// Pull the audio/transcription columns out of a single `RecordBatch`, hand
+/// each row's bytes to `sink` and append the resulting metadata. Rows
+/// whose `bytes` or `path` are null are skipped rather than aborting the batch.
+fn process_batch(
+    batch: &RecordBatch,
+    sink: &dyn AudioSink,
+    partition_by: Option<&str>,
+    metadata_records: &MetaRows,
+) -> Result<usize> {
+    let audio = batch
+        .column_by_name("audio")
+        .context("batch is missing the `audio` column")?
+        .as_any()
+        .downcast_ref::<StructArray>()
+        .context("`audio` column is not a struct array")?;
+    let path_array = audio
+        .column_by_name("path")
+        .context("`audio` struct is missing the `path` field")?
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .context("`audio.path` is not a Utf8 array")?;
+    let bytes_array = audio
+        .column_by_name("bytes")
+        .context("`audio` struct is missing the `bytes` field")?
+        .as_any()
+        .downcast_ref::<BinaryArray>()
+        .context("`audio.bytes` is not a Binary array")?;
+    let transcription_array = batch
+        .column_by_name("transcription")
+        .context("batch is missing the `transcription` column")?
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .context("`transcription` column is not a Utf8 array")?;
+    // Cast the partition column to Utf8 up front, mirroring the Polars cast in
+    // `process_file`, so the on-disk layout is identical regardless of whether
+    // `--batch-size` selected the streaming path. An unreadable column errors
+    // rather than silently dropping the partition.
+    let partition_values = match partition_by {
+        Some(column) => {
+            let array = batch
+                .column_by_name(column)
+                .with_context(|| format!("batch is missing the `{column}` column"))?;
+            let casted = arrow::compute::cast(array, &DataType::Utf8)
+                .with_context(|| format!("partition column `{column}` cannot be read as text"))?;
+            let strings = casted
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .context("partition column did not cast to a Utf8 array")?
+                .clone();
+            Some(strings)
+        }
+        None => None,
+    };
+
+    let local_metadata: Vec<(String, String, Option<String>)> = (0..batch.num_rows())
+        .into_par_iter()
+        .filter(|&i| !path_array.is_null(i) && !bytes_array.is_null(i))
+        .map(|i| {
+            let original_path = Path::new(path_array.value(i));
+            let file_stem = original_path.file_stem().unwrap_or_default();
+            let extension = original_path.extension().unwrap_or_default();
+
+            let audio_filename_str = format!(
+                "{}.{}",
+                file_stem.to_string_lossy(),
+                extension.to_string_lossy()
+            );
+            let transcription = if transcription_array.is_null(i) {
+                String::new()
+            } else {
+                transcription_array.value(i).to_string()
+            };
+            let partition = partition_values.as_ref().and_then(|values| {
+                if values.is_null(i) {
+                    None
+                } else {
+                    Some(values.value(i).to_string())
+                }
+            });
+            let suggested = partitioned_name(
+                &audio_filename_str,
+                partition_by.zip(partition.as_deref()),
+            );
+            let final_name = sink.write(&suggested, bytes_array.value(i), &transcription)?;
+
+            Ok((final_name, transcription, partition))
+        })
+        .collect::<Result<_>>()?;
+
+    let written = local_metadata.len();
+    metadata_records.lock().unwrap().extend(local_metadata);
+
+    Ok(written)
+}
+
+/// Streaming counterpart to [`process_file`]: reads the input one record batch
+/// at a time and flushes audio to disk per batch, never decoding more than a
+/// single batch at once. Remote inputs are already fetched whole into memory
+/// as `Bytes`; only local files are read from disk incrementally.
+fn process_file_streaming(
+    input: &InputData,
+    format: Format,
+    sink: &dyn AudioSink,
+    batch_size: usize,
+    partition_by: Option<&str>,
+    metadata_records: &MetaRows,
+) -> Result<usize> {
+    let mut total_rows = 0usize;
+    match format {
+        Format::Parquet => {
+            // Both a local `File` and an in-memory `Bytes` implement
+            // `ChunkReader`, and `build()` yields the same concrete reader type.
+            let reader: ParquetRecordBatchReader = match input {
+                InputData::Local(path) => {
+                    let file = File::open(path).with_context(|| {
+                        format!("Failed to open input file: {}", path.display())
+                    })?;
+                    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+                        .context("Failed to create parquet record batch reader")?
+                        .with_batch_size(batch_size);
+                    validate_audio_schema(&builder.schema().clone())?;
+                    builder.build().context("Failed to build parquet reader")?
+                }
+                InputData::Remote { bytes, .. } => {
+                    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes.clone())
+                        .context("Failed to create parquet record batch reader")?
+                        .with_batch_size(batch_size);
+                    validate_audio_schema(&builder.schema().clone())?;
+                    builder.build().context("Failed to build parquet reader")?
+                }
+            };
+            for batch in reader {
+                let batch = batch.context("Failed to read parquet record batch")?;
+                total_rows += process_batch(&batch, sink, partition_by, metadata_records)?;
+            }
+        }
+        Format::Arrow => {
+            let source: Box<dyn Read> = match input {
+                InputData::Local(path) => Box::new(File::open(path).with_context(|| {
+                    format!("Failed to open input file: {}", path.display())
+                })?),
+                InputData::Remote { bytes, .. } => Box::new(Cursor::new(bytes.clone())),
+            };
+            let reader = StreamReader::try_new(source, None)
+                .context("Failed to create arrow stream reader")?;
+            validate_audio_schema(&reader.schema())?;
+            for batch in reader {
+                let batch = batch.context("Failed to read arrow record batch")?;
+                total_rows += process_batch(&batch, sink, partition_by, metadata_records)?;
+            }
+        }
+    }
+
+    Ok(total_rows)
+}
+
+/// Dispatch to the streaming extractor when `--batch-size` is set, otherwise
+/// fall back to the DataFrame-based [`process_file`].
+fn extract_input(
+    input: &InputData,
+    format: Format,
+    sink: &dyn AudioSink,
+    batch_size: Option<usize>,
+    partition_by: Option<&str>,
+    metadata_records: &MetaRows,
+) -> Result<usize> {
+    match batch_size {
+        Some(n) => {
+            process_file_streaming(input, format, sink, n, partition_by, metadata_records)
+        }
+        None => process_file(input, format, sink, partition_by, metadata_records),
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
@@ -219,46 +805,97 @@ fn main() -> Result<()> {
 
     let metadata_records = Mutex::new(Vec::new());
 
-    if let Some(input_file) = args.input {
-        if !input_file.is_file() {
-            eprintln!("Input is not a file: {}", input_file.display());
-            process::exit(1);
+    // Pick the on-disk layout for extracted audio. Both sinks are referenced
+    // behind `&dyn AudioSink` so the processing path stays layout-agnostic.
+    let files_sink;
+    let tar_sink;
+    let sink: &dyn AudioSink = match args.output_mode {
+        OutputMode::Files => {
+            files_sink = FilesSink {
+                output_dir: &args.output,
+            };
+            &files_sink
+        }
+        OutputMode::Tar => {
+            tar_sink = TarSink::new(&args.output, args.shard_size)?;
+            &tar_sink
         }
-        println!("Processing file: {}...", input_file.display());
-        let rows = process_file(&input_file, args.format, &args.output, &metadata_records)?;
+    };
+
+    if let Some(input_file) = args.input.as_ref() {
+        let input_str = input_file.to_string_lossy();
+        let input = if remote_scheme(&input_str).is_some() {
+            fetch_remote(&input_str)?
+        } else {
+            // `file://` URIs name local paths; strip the scheme before use.
+            let input_file = input_str
+                .strip_prefix("file://")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input_file.clone());
+            if !input_file.is_file() {
+                eprintln!("Input is not a file: {}", input_file.display());
+                process::exit(1);
+            }
+            InputData::Local(input_file)
+        };
+        println!("Processing file: {}...", input.display_name());
+        let rows = extract_input(
+                &input,
+                args.format,
+                sink,
+                args.batch_size,
+                args.partition_by.as_deref(),
+                &metadata_records,
+            )?;
         println!("Total number of rows processed: {}", rows);
     }
 
-    if let Some(input_dir) = args.input_dir {
-        if !input_dir.is_dir() {
-            eprintln!(
-                "Input directory does not exist or is not a directory: {}",
-                input_dir.display()
-            );
-            process::exit(1);
-        }
+    if let Some(input_dir) = args.input_dir.as_ref() {
+        let dir_str = input_dir.to_string_lossy();
+        let inputs: Vec<InputData> = if remote_scheme(&dir_str).is_some() {
+            fetch_remote_dir(&dir_str)?
+        } else {
+            let input_dir = dir_str
+                .strip_prefix("file://")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| input_dir.clone());
+            if !input_dir.is_dir() {
+                eprintln!(
+                    "Input directory does not exist or is not a directory: {}",
+                    input_dir.display()
+                );
+                process::exit(1);
+            }
 
-        let files_to_process: Vec<_> = read_dir(input_dir)?
-            .filter_map(Result::ok)
-            .filter(|entry| {
-                entry.path().is_file()
-                    && entry // TODO: this is not correct, should be based on format
-                        .path()
-                        .extension()
-                        .is_some_and(|ext| ext == "parquet" || ext == "arrow")
-            })
-            .collect();
+            read_dir(&input_dir)?
+                .filter_map(Result::ok)
+                .filter(|entry| {
+                    entry.path().is_file()
+                        && entry // TODO: this is not correct, should be based on format
+                            .path()
+                            .extension()
+                            .is_some_and(|ext| ext == "parquet" || ext == "arrow")
+                })
+                .map(|entry| InputData::Local(entry.path()))
+                .collect()
+        };
 
         let total_rows = AtomicUsize::new(0);
 
-        files_to_process.into_iter().for_each(|entry| {
-            let path = entry.path();
-            println!("Processing file: {}...", path.display());
-            match process_file(&path, args.format, &args.output, &metadata_records) {
+        inputs.into_iter().for_each(|input| {
+            println!("Processing file: {}...", input.display_name());
+            match extract_input(
+                &input,
+                args.format,
+                sink,
+                args.batch_size,
+                args.partition_by.as_deref(),
+                &metadata_records,
+            ) {
                 Ok(rows) => {
                     total_rows.fetch_add(rows, Ordering::SeqCst);
                 }
-                Err(e) => eprintln!("Error processing file {}: {}", entry.path().display(), e),
+                Err(e) => eprintln!("Error processing file {}: {}", input.display_name(), e),
             }
         });
 
@@ -268,24 +905,38 @@ fn main() -> Result<()> {
         );
     }
 
+    // Flush any buffered sink state (e.g. the final tar shard).
+    sink.finish()?;
+
     if let Some(metadata_file_path) = args.metadata_file {
         println!("Writing metadata to {}...", metadata_file_path.display());
         let records = metadata_records.into_inner().unwrap();
         if !records.is_empty() {
             let height = records.len();
-            let mut df = DataFrame::new(
-                height,
-                vec![
-                    Column::new(
-                        "file_name".into(),
-                        records.iter().map(|(f, _)| f.as_str()).collect::<Vec<_>>(),
-                    ),
-                    Column::new(
-                        "transcription".into(),
-                        records.iter().map(|(_, t)| t.as_str()).collect::<Vec<_>>(),
-                    ),
-                ],
-            )?;
+            let mut columns = vec![
+                Column::new(
+                    "file_name".into(),
+                    records.iter().map(|(f, ..)| f.as_str()).collect::<Vec<_>>(),
+                ),
+                Column::new(
+                    "transcription".into(),
+                    records
+                        .iter()
+                        .map(|(_, t, _)| t.as_str())
+                        .collect::<Vec<_>>(),
+                ),
+            ];
+            // Carry the partition key so downstream consumers know the mapping.
+            if let Some(partition_column) = args.partition_by.as_ref() {
+                columns.push(Column::new(
+                    partition_column.as_str().into(),
+                    records
+                        .iter()
+                        .map(|(.., p)| p.as_deref())
+                        .collect::<Vec<_>>(),
+                ));
+            }
+            let mut df = DataFrame::new(height, columns)?;
 
             let mut file = File::create(&metadata_file_path).with_context(|| {
                 format!(
@@ -293,7 +944,19 @@ fn main() -> Result<()> {
                     metadata_file_path.display()
                 )
             })?;
-            CsvWriter::new(&mut file).finish(&mut df)?;
+            match args.metadata_format {
+                MetadataFormat::Csv => {
+                    CsvWriter::new(&mut file).finish(&mut df)?;
+                }
+                MetadataFormat::Jsonl => {
+                    JsonWriter::new(&mut file)
+                        .with_json_format(JsonFormat::JsonLines)
+                        .finish(&mut df)?;
+                }
+                MetadataFormat::Parquet => {
+                    ParquetWriter::new(&mut file).finish(&mut df)?;
+                }
+            }
         }
     }
 
@@ -399,7 +1062,8 @@ mod tests {
     #[test]
     fn batches_to_parquet_flattens_audio_struct() {
         let batches = sample_batches();
-        let df = batches_to_parquet(&batches).expect("conversion should succeed");
+        let df =
+            batches_to_parquet(&batches, &projection_columns(None)).expect("conversion should succeed");
         assert_eq!(df.height(), 2);
 
         let paths = df
@@ -425,7 +1089,8 @@ mod tests {
         let temp_dir = tempdir().expect("failed to create tempdir");
         let parquet_path = write_parquet_file(temp_dir.path(), "input.parquet", &batches);
 
-        let df = read_parquet(&parquet_path).expect("should read parquet file");
+        let df =
+            read_parquet(&parquet_path, &projection_columns(None)).expect("should read parquet file");
         assert_eq!(df.height(), 2);
 
         let transcription = df
@@ -443,7 +1108,8 @@ mod tests {
         let temp_dir = tempdir().expect("failed to create tempdir");
         let arrow_path = write_arrow_file(temp_dir.path(), "input.arrow", &batches);
 
-        let df = arrow_to_parquet(&arrow_path).expect("should load arrow stream");
+        let df =
+            arrow_to_parquet(&arrow_path, &projection_columns(None)).expect("should load arrow stream");
         assert_eq!(df.height(), 2);
 
         let paths = df
@@ -464,7 +1130,11 @@ mod tests {
         let metadata = Mutex::new(Vec::new());
 
         UNIQUE_FILENAME_COUNTER.store(0, Ordering::SeqCst);
-        let processed = process_file(&parquet_path, Format::Parquet, &output_dir, &metadata)
+        let sink = FilesSink {
+            output_dir: &output_dir,
+        };
+        let input = InputData::Local(parquet_path.clone());
+        let processed = process_file(&input, Format::Parquet, &sink, None, &metadata)
             .expect("processing should succeed");
         assert_eq!(processed, 2);
 
@@ -485,8 +1155,99 @@ mod tests {
         assert_eq!(audio_bytes, vec![1, 2, 3]);
 
         let metadata = metadata.lock().expect("metadata mutex poisoned");
-        assert!(metadata.contains(&("sample1.wav".to_string(), "hello world".to_string())));
-        assert!(metadata.contains(&("sample2.wav".to_string(), "goodbye world".to_string())));
+        assert!(metadata.contains(&("sample1.wav".to_string(), "hello world".to_string(), None)));
+        assert!(metadata.contains(&("sample2.wav".to_string(), "goodbye world".to_string(), None)));
+    }
+
+    #[test]
+    fn process_file_streaming_writes_audio_and_metadata() {
+        let batches = sample_batches();
+        let temp_dir = tempdir().expect("failed to create tempdir");
+        let parquet_path = write_parquet_file(temp_dir.path(), "input.parquet", &batches);
+        let output_dir = temp_dir.path().join("out");
+        fs::create_dir(&output_dir).expect("failed to create output dir");
+        let metadata = Mutex::new(Vec::new());
+
+        UNIQUE_FILENAME_COUNTER.store(0, Ordering::SeqCst);
+        let sink = FilesSink {
+            output_dir: &output_dir,
+        };
+        let input = InputData::Local(parquet_path.clone());
+        let processed = process_file_streaming(&input, Format::Parquet, &sink, 1, None, &metadata)
+            .expect("streaming processing should succeed");
+        assert_eq!(processed, 2);
+
+        let audio_bytes = fs::read(output_dir.join("sample1.wav")).expect("file missing");
+        assert_eq!(audio_bytes, vec![1, 2, 3]);
+
+        let metadata = metadata.lock().expect("metadata mutex poisoned");
+        assert!(metadata.contains(&("sample1.wav".to_string(), "hello world".to_string(), None)));
+        assert!(metadata.contains(&("sample2.wav".to_string(), "goodbye world".to_string(), None)));
+    }
+
+    #[test]
+    fn tar_sink_packs_wav_and_txt_entries() {
+        use std::io::Read;
+
+        let temp_dir = tempdir().expect("failed to create tempdir");
+        let output_dir = temp_dir.path().join("out");
+        fs::create_dir(&output_dir).expect("failed to create output dir");
+
+        let sink = TarSink::new(&output_dir, 1_000_000).expect("failed to create tar sink");
+        sink.write("sample1.wav", &[1u8, 2, 3], "hello world")
+            .expect("write should succeed");
+        sink.write("sample2.wav", &[4u8, 5, 6], "goodbye world")
+            .expect("write should succeed");
+        sink.finish().expect("finish should succeed");
+
+        let shard = output_dir.join("shard-000000.tar");
+        let mut archive = tar::Archive::new(File::open(&shard).expect("shard missing"));
+        let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+        for entry in archive.entries().expect("failed to read entries") {
+            let mut entry = entry.expect("bad entry");
+            let name = entry
+                .path()
+                .expect("bad path")
+                .to_string_lossy()
+                .into_owned();
+            let mode = entry.header().mode().expect("missing mode");
+            assert_eq!(mode & 0o777, 0o644);
+            let mut buf = Vec::new();
+            entry.read_to_end(&mut buf).expect("failed to read entry");
+            entries.push((name, buf));
+        }
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].0, "00000000.wav");
+        assert_eq!(entries[0].1, vec![1, 2, 3]);
+        assert_eq!(entries[1].0, "00000000.txt");
+        assert_eq!(entries[1].1, b"hello world");
+        assert_eq!(entries[2].0, "00000001.wav");
+    }
+
+    #[test]
+    fn partitioned_name_builds_hive_style_paths() {
+        assert_eq!(partitioned_name("clip.wav", None), "clip.wav");
+        assert_eq!(
+            partitioned_name("clip.wav", Some(("split", "train"))),
+            "split=train/clip.wav"
+        );
+    }
+
+    #[test]
+    fn remote_scheme_detects_object_store_uris() {
+        assert_eq!(remote_scheme("s3://bucket/key.parquet"), Some("s3"));
+        assert_eq!(remote_scheme("gs://bucket/key.parquet"), Some("gs"));
+        assert_eq!(remote_scheme("https://host/key.parquet"), Some("https"));
+        assert_eq!(remote_scheme("file:///tmp/key.parquet"), None);
+        assert_eq!(remote_scheme("/tmp/key.parquet"), None);
+        assert_eq!(remote_scheme("relative/key.parquet"), None);
+    }
+
+    #[test]
+    fn validate_audio_schema_rejects_missing_fields() {
+        let schema = Schema::new(vec![Field::new("transcription", DataType::Utf8, true)]);
+        assert!(validate_audio_schema(&schema).is_err());
     }
 
     #[test]